@@ -0,0 +1,119 @@
+//! An interned string type used to deduplicate strings that repeat across a
+//! large instance tree, like `ClassName`s and property keys.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+static INTERN_TABLE: Lazy<Mutex<HashSet<Arc<str>>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// A cheaply-cloneable, deduplicated string.
+///
+/// Interning the same contents twice returns a handle to the same
+/// underlying allocation, and the hash is computed once up front, so
+/// equality and hashing reduce to a precomputed hash comparison plus a
+/// pointer check instead of a byte-by-byte comparison. This is a
+/// significant memory and speed win for large instance trees, where the
+/// same `ClassName`s and property keys repeat across thousands of
+/// instances.
+///
+/// There's deliberately no `Borrow<str>` impl: the precomputed hash isn't
+/// computed the same way `str`'s `Hash` impl would feed a `Hasher`, so a
+/// `Borrow<str>` would violate `Hash`/`Borrow`/`Eq` consistency and make
+/// `HashMap<InternedString, _>::get("some str")` silently miss. Look up
+/// with an `InternedString` (via `InternedString::new`) instead.
+#[derive(Debug, Clone, Eq)]
+pub struct InternedString {
+    value: Arc<str>,
+    hash: u64,
+}
+
+impl InternedString {
+    pub fn new(value: &str) -> Self {
+        let value = {
+            let mut table = INTERN_TABLE.lock().unwrap();
+
+            match table.get(value) {
+                Some(existing) => Arc::clone(existing),
+                None => {
+                    let arc: Arc<str> = Arc::from(value);
+                    table.insert(Arc::clone(&arc));
+                    arc
+                }
+            }
+        };
+
+        let hash = {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        InternedString { value, hash }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+}
+
+impl PartialEq for InternedString {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+impl Hash for InternedString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl Deref for InternedString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for InternedString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl From<&str> for InternedString {
+    fn from(value: &str) -> Self {
+        InternedString::new(value)
+    }
+}
+
+impl From<String> for InternedString {
+    fn from(value: String) -> Self {
+        InternedString::new(&value)
+    }
+}
+
+impl Serialize for InternedString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.value)
+    }
+}
+
+impl<'de> Deserialize<'de> for InternedString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // `String` rather than `&str`: a class name or property key
+        // containing an escape sequence has no unescaped borrow to hand
+        // back, so borrowing here would fail to deserialize at all.
+        let value = String::deserialize(deserializer)?;
+        Ok(InternedString::new(&value))
+    }
+}
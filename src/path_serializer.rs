@@ -0,0 +1,60 @@
+//! Custom serializers for paths embedded in project files.
+//!
+//! Paths in a loaded `Project` are relative to wherever the project file
+//! happened to live, which makes them unsuitable to compare or display
+//! as-is. These serializers resolve paths to an absolute form first, so
+//! that things like `rojo project` output stay meaningful regardless of
+//! the current working directory.
+
+use std::path::{Path, PathBuf};
+
+use serde::ser::{SerializeSeq, Serializer};
+
+use crate::project::PathSpec;
+
+/// Serializes a single path, resolving it to an absolute path first.
+pub fn serialize_absolute<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    let absolute = path
+        .canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf());
+
+    serializer.serialize_str(&absolute.to_string_lossy())
+}
+
+/// Serializes an `Option<PathBuf>`, resolving it to an absolute path first.
+pub fn serialize_option_absolute<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_absolute(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes an `Option<PathSpec>`, resolving every path it contains to an
+/// absolute path first. A `PathSpec::Single` serializes the same way
+/// `serialize_option_absolute` would; a `PathSpec::Multiple` serializes as
+/// an array of absolute paths, preserving its union order.
+pub fn serialize_option_path_spec<S: Serializer>(
+    path: &Option<PathSpec>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(PathSpec::Single(path)) => serialize_absolute(path, serializer),
+        Some(PathSpec::Multiple(paths)) => {
+            let mut seq = serializer.serialize_seq(Some(paths.len()))?;
+
+            for path in paths {
+                let absolute = path
+                    .canonicalize()
+                    .unwrap_or_else(|_| path.to_path_buf());
+
+                seq.serialize_element(&absolute.to_string_lossy())?;
+            }
+
+            seq.end()
+        }
+        None => serializer.serialize_none(),
+    }
+}
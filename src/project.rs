@@ -2,12 +2,17 @@ use std::{
     collections::{BTreeMap, HashMap, HashSet},
     fs, io,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use once_cell::sync::OnceCell;
 use rbx_dom_weak::UnresolvedRbxValue;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 
+use crate::intern::InternedString;
+
 pub static PROJECT_FILENAME: &str = "default.project.json";
 
 /// Error type returned by any function that handles projects.
@@ -24,6 +29,14 @@ enum Error {
         source: serde_json::Error,
         path: PathBuf,
     },
+
+    /// Two entries in a `$path` array produced a child instance with the
+    /// same name.
+    DuplicatePathChild {
+        name: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -49,6 +62,17 @@ pub struct Project {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub serve_place_ids: Option<HashSet<u64>>,
 
+    /// If specified, requires every request made to `rojo serve`'s UI
+    /// service to present this value as a bearer token in its
+    /// `Authorization` header. The UI's content-hashed static assets (logo,
+    /// icon and stylesheet) are served without this check.
+    ///
+    /// Unlike `serve_place_ids`, which is advisory and client-supplied, this
+    /// is an actual access control and makes it safe to run live sync on a
+    /// machine reachable by more than just its owner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_auth: Option<String>,
+
     /// The path to the file that this project came from. Relative paths in the
     /// project should be considered relative to the parent of this field, also
     /// given by `Project::folder_location`.
@@ -142,10 +166,144 @@ impl Project {
     pub fn folder_location(&self) -> &Path {
         self.file_location.parent().unwrap()
     }
+
+    /// Returns every file on disk that this project's tree would sync into
+    /// an instance, honoring every node's `$ignorePaths` and erroring if any
+    /// node's `$path` entries would union two children under the same name.
+    ///
+    /// This is the list an IMFS snapshot should be built from, rather than a
+    /// raw recursive directory read, so that `$ignorePaths` is enforced for
+    /// every instance the snapshot ever produces.
+    pub fn synced_files(&self) -> Result<Vec<PathBuf>, ProjectError> {
+        let root_globset = self.tree.ignore_globset();
+        self.tree
+            .walk_synced_files(self.folder_location(), &root_globset)
+    }
+
+    /// Tells whether a filesystem removal at `path` should be suppressed
+    /// instead of deleting the instance it would otherwise correspond to.
+    ///
+    /// `path` was excluded by `$ignorePaths` if it falls under some node's
+    /// `$path` and that node's effective `GlobSet` matches it, or matches
+    /// one of its ancestors within that `$path` (a matched directory is
+    /// pruned whole); such a path was never synced into an instance in the
+    /// first place, so its disappearance shouldn't touch the instance tree.
+    /// A live-sync watcher should call this before reacting to a filesystem
+    /// removal event.
+    pub fn should_suppress_removal(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(self.folder_location()).unwrap_or(path);
+        let root_globset = self.tree.ignore_globset();
+
+        match self.tree.globset_governing(relative, &root_globset) {
+            Some((globset, source)) => relative
+                .ancestors()
+                .take_while(|ancestor| ancestor.starts_with(&source))
+                .any(|ancestor| ProjectNode::is_path_ignored(ancestor, &globset)),
+            None => false,
+        }
+    }
+}
+
+/// The value of a `$path` field, which can either be a single path or a list
+/// of paths whose contents are unioned onto the same instance.
+///
+/// Accepting an array here lets a node assemble its children from several
+/// independent source directories, for example combining `ReplicatedStorage`
+/// content from multiple packages without symlink hacks or wrapper folders.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PathSpec {
+    Single(PathBuf),
+    Multiple(Vec<PathBuf>),
+}
+
+impl PathSpec {
+    /// Returns every path described by this spec, in the order they were
+    /// listed. No path takes priority over another: a child name produced
+    /// by more than one path is an error in `union_children`.
+    pub fn paths(&self) -> &[PathBuf] {
+        match self {
+            PathSpec::Single(path) => std::slice::from_ref(path),
+            PathSpec::Multiple(paths) => paths,
+        }
+    }
+
+    /// Derives the instance name Rojo would give the file or directory at
+    /// `path`. A directory's instance name is its own name; a file's is its
+    /// name with any recognized Rojo source extension stripped, since e.g.
+    /// `foo.lua` and `foo.rbxm` both resolve to an instance named `foo`.
+    fn instance_name_from_path(path: &Path) -> String {
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name,
+            None => return path.to_string_lossy().into_owned(),
+        };
+
+        if path.is_dir() {
+            return file_name.to_owned();
+        }
+
+        const KNOWN_SUFFIXES: &[&str] = &[
+            ".server.lua",
+            ".client.lua",
+            ".lua",
+            ".rbxmx",
+            ".rbxm",
+            ".csv",
+            ".toml",
+            ".json",
+            ".txt",
+        ];
+
+        for suffix in KNOWN_SUFFIXES {
+            if let Some(stem) = file_name.strip_suffix(suffix) {
+                return stem.to_owned();
+            }
+        }
+
+        file_name.to_owned()
+    }
+
+    /// Reads the immediate children of every path in this spec and unions
+    /// them into a single mapping from child name to source path, as if all
+    /// paths were merged into one directory. Returns an error if two paths
+    /// would produce a child instance with the same name.
+    pub fn union_children(&self) -> Result<BTreeMap<String, PathBuf>, ProjectError> {
+        let mut children: BTreeMap<String, PathBuf> = BTreeMap::new();
+
+        for source in self.paths() {
+            if !source.is_dir() {
+                continue;
+            }
+
+            let entries = fs::read_dir(source).context(Io {
+                path: source.clone(),
+            })?;
+
+            for entry in entries {
+                let entry = entry.context(Io {
+                    path: source.clone(),
+                })?;
+                let child_path = entry.path();
+                let name = Self::instance_name_from_path(&child_path);
+
+                if let Some(first) = children.insert(name.clone(), child_path.clone()) {
+                    return DuplicatePathChild {
+                        name,
+                        first,
+                        second: child_path,
+                    }
+                    .fail()
+                    .map_err(ProjectError::from);
+                }
+            }
+        }
+
+        Ok(children)
+    }
 }
 
 /// Describes an instance and its descendants in a project.
-#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProjectNode {
     /// If set, defines the ClassName of the described instance.
     ///
@@ -154,7 +312,7 @@ pub struct ProjectNode {
     /// `$className` CANNOT be set if `$path` is set and the instance described
     /// by that path has a ClassName other than Folder.
     #[serde(rename = "$className", skip_serializing_if = "Option::is_none")]
-    pub class_name: Option<String>,
+    pub class_name: Option<InternedString>,
 
     /// Contains all of the children of the described instance.
     #[serde(flatten)]
@@ -162,13 +320,15 @@ pub struct ProjectNode {
 
     /// The properties that will be assigned to the resulting instance.
     ///
+    /// Property keys are interned since the same keys (e.g. `Value`,
+    /// `Source`) repeat across huge numbers of instances in large projects.
     // TODO: Is this legal to set if $path is set?
     #[serde(
         rename = "$properties",
         default,
         skip_serializing_if = "HashMap::is_empty"
     )]
-    pub properties: HashMap<String, UnresolvedRbxValue>,
+    pub properties: HashMap<InternedString, UnresolvedRbxValue>,
 
     /// Defines the behavior when Rojo encounters unknown instances in Roblox
     /// Studio during live sync. `$ignoreUnknownInstances` should be considered
@@ -190,19 +350,197 @@ pub struct ProjectNode {
     )]
     pub ignore_unknown_instances: Option<bool>,
 
-    /// Defines that this instance should come from the given file path. This
-    /// path can point to any file type supported by Rojo, including Lua files
-    /// (`.lua`), Roblox models (`.rbxm`, `.rbxmx`), and localization table
-    /// spreadsheets (`.csv`).
+    /// Defines that this instance should come from the given file path, or
+    /// from several file paths whose contents are unioned onto the same
+    /// instance. Each path can point to any file type supported by Rojo,
+    /// including Lua files (`.lua`), Roblox models (`.rbxm`, `.rbxmx`), and
+    /// localization table spreadsheets (`.csv`).
     #[serde(
         rename = "$path",
-        serialize_with = "crate::path_serializer::serialize_option_absolute",
+        serialize_with = "crate::path_serializer::serialize_option_path_spec",
         skip_serializing_if = "Option::is_none"
     )]
-    pub path: Option<PathBuf>,
+    pub path: Option<PathSpec>,
+
+    /// A set of glob patterns, interpreted relative to the project's
+    /// `folder_location()`, whose matches should never be turned into
+    /// instances when this node's `$path` is snapshotted.
+    ///
+    /// Unlike `$ignoreUnknownInstances`, which only tells Rojo to tolerate
+    /// instances it doesn't recognize during live sync, `$ignorePaths`
+    /// prevents matched files from ever becoming instances in the first
+    /// place. Patterns are inherited by descendant nodes unless a
+    /// descendant sets its own `$ignorePaths`.
+    #[serde(
+        rename = "$ignorePaths",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub ignore_paths: Vec<String>,
+
+    /// Lazily-compiled `GlobSet` for `ignore_paths`, cached here so that
+    /// matching a path against it is O(1) instead of recompiling the glob
+    /// patterns on every file visited during the IMFS walk.
+    ///
+    /// `OnceCell` rather than `RefCell`: `ProjectNode` (and, transitively,
+    /// `Project`/`ServeSession`) needs to stay `Sync` to satisfy the `Send`
+    /// bound on `UiService`'s response future in the threaded serve path,
+    /// which a `RefCell` would break.
+    #[serde(skip)]
+    ignore_globset: OnceCell<Arc<GlobSet>>,
+}
+
+impl PartialEq for ProjectNode {
+    fn eq(&self, other: &Self) -> bool {
+        // `ignore_globset` is a derived cache of `ignore_paths` and carries
+        // no independent state, so it's excluded from equality.
+        self.class_name == other.class_name
+            && self.children == other.children
+            && self.properties == other.properties
+            && self.ignore_unknown_instances == other.ignore_unknown_instances
+            && self.path == other.path
+            && self.ignore_paths == other.ignore_paths
+    }
 }
 
 impl ProjectNode {
+    /// Returns the compiled `GlobSet` describing this node's `$ignorePaths`,
+    /// compiling it on first use and reusing the cached value afterwards.
+    pub fn ignore_globset(&self) -> Arc<GlobSet> {
+        Arc::clone(self.ignore_globset.get_or_init(|| {
+            let mut builder = GlobSetBuilder::new();
+
+            for pattern in &self.ignore_paths {
+                match Glob::new(pattern) {
+                    Ok(glob) => {
+                        builder.add(glob);
+                    }
+                    Err(err) => {
+                        log::warn!("Invalid $ignorePaths pattern '{}': {}", pattern, err);
+                    }
+                }
+            }
+
+            Arc::new(builder.build().unwrap_or_else(|_| GlobSet::empty()))
+        }))
+    }
+
+    /// Returns the `GlobSet` that should apply to this node's own `$path`
+    /// walk: this node's `$ignorePaths` if it set any, otherwise whatever
+    /// was inherited from the nearest ancestor that did.
+    pub fn effective_ignore_globset(&self, inherited: &Arc<GlobSet>) -> Arc<GlobSet> {
+        if self.ignore_paths.is_empty() {
+            Arc::clone(inherited)
+        } else {
+            self.ignore_globset()
+        }
+    }
+
+    /// Tells whether `path`, relative to the project's `folder_location()`,
+    /// is excluded by the given `$ignorePaths` `GlobSet`.
+    pub fn is_path_ignored(path: &Path, globset: &GlobSet) -> bool {
+        globset.is_match(path)
+    }
+
+    /// Recursively walks the filesystem paths referenced by this node and
+    /// its descendants, returning every file that would become an instance,
+    /// after applying `$ignorePaths` inherited down the tree from
+    /// `inherited_globset`. A directory matched by the effective `GlobSet`
+    /// is pruned without descending into or stat-ing its children.
+    pub fn walk_synced_files(
+        &self,
+        project_folder: &Path,
+        inherited_globset: &Arc<GlobSet>,
+    ) -> Result<Vec<PathBuf>, ProjectError> {
+        let globset = self.effective_ignore_globset(inherited_globset);
+        let mut files = Vec::new();
+
+        if let Some(path_spec) = &self.path {
+            // Relative `$path` entries are relative to the project folder,
+            // not the current working directory.
+            let sources: Vec<PathBuf> = path_spec
+                .paths()
+                .iter()
+                .map(|source| project_folder.join(source))
+                .collect();
+
+            // Multiple sources are unioned onto this same instance, so a
+            // child name that more than one of them would produce is
+            // ambiguous and rejected up front, before any of them are
+            // walked.
+            if sources.len() > 1 {
+                PathSpec::Multiple(sources.clone()).union_children()?;
+            }
+
+            for source in &sources {
+                Self::walk_path(source, project_folder, &globset, &mut files)?;
+            }
+        }
+
+        for child in self.children.values() {
+            files.extend(child.walk_synced_files(project_folder, &globset)?);
+        }
+
+        Ok(files)
+    }
+
+    /// Finds the effective `$ignorePaths` `GlobSet` that would govern
+    /// `path` during a sync, by walking this node and its descendants in
+    /// step with the `$path` sources they reference, together with the
+    /// `$path` source that `path` falls under. Returns `None` if `path`
+    /// doesn't fall under any node's `$path` in this subtree.
+    fn globset_governing(
+        &self,
+        path: &Path,
+        inherited_globset: &Arc<GlobSet>,
+    ) -> Option<(Arc<GlobSet>, PathBuf)> {
+        let globset = self.effective_ignore_globset(inherited_globset);
+
+        if let Some(path_spec) = &self.path {
+            if let Some(source) = path_spec.paths().iter().find(|source| path.starts_with(source)) {
+                return Some((globset, source.clone()));
+            }
+        }
+
+        self.children
+            .values()
+            .find_map(|child| child.globset_governing(path, &globset))
+    }
+
+    fn walk_path(
+        path: &Path,
+        project_folder: &Path,
+        globset: &GlobSet,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), ProjectError> {
+        let relative = path.strip_prefix(project_folder).unwrap_or(path);
+
+        if Self::is_path_ignored(relative, globset) {
+            // Matched: prune this whole subtree without stat-ing its
+            // children.
+            return Ok(());
+        }
+
+        let metadata = fs::metadata(path).context(Io {
+            path: path.to_path_buf(),
+        })?;
+
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path).context(Io {
+                path: path.to_path_buf(),
+            })? {
+                let entry = entry.context(Io {
+                    path: path.to_path_buf(),
+                })?;
+                Self::walk_path(&entry.path(), project_folder, globset, files)?;
+            }
+        } else {
+            files.push(path.to_path_buf());
+        }
+
+        Ok(())
+    }
+
     fn validate_reserved_names(&self) {
         for (name, child) in &self.children {
             if name.starts_with('$') {
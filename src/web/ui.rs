@@ -1,11 +1,14 @@
 //! Defines the HTTP-based UI. These endpoints generally return HTML and SVG.
 
-use std::{sync::Arc, time::Duration};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use futures::{future, Future};
 use hyper::{header, service::Service, Body, Method, Request, Response, StatusCode};
+use once_cell::sync::Lazy;
 use rbx_dom_weak::{RbxId, RbxValue};
 use ritz::{html, Fragment, HtmlContent};
+use serde::Serialize;
+use subtle::ConstantTimeEq;
 
 use crate::{
     imfs::ImfsFetcher,
@@ -18,8 +21,35 @@ use crate::{
     },
 };
 
+// Hashing each asset once at startup lets us serve it at a URL that changes
+// whenever the bytes do, so browsers can cache it "forever" and a new Rojo
+// version invalidates the old cached copy automatically.
+static LOGO_HASH: Lazy<String> = Lazy::new(|| blake3::hash(assets::logo()).to_hex().to_string());
+static ICON_HASH: Lazy<String> = Lazy::new(|| blake3::hash(assets::icon()).to_hex().to_string());
+static CSS_HASH: Lazy<String> =
+    Lazy::new(|| blake3::hash(assets::css().as_bytes()).to_hex().to_string());
+
+static LOGO_PATH: Lazy<String> = Lazy::new(|| format!("/assets/logo.{}.png", *LOGO_HASH));
+static ICON_PATH: Lazy<String> = Lazy::new(|| format!("/assets/icon.{}.png", *ICON_HASH));
+static CSS_PATH: Lazy<String> = Lazy::new(|| format!("/assets/style.{}.css", *CSS_HASH));
+
+/// Serializable mirror of the tree rendered by `instance()`, used for the
+/// `/api/show-instances.json` endpoint.
+#[derive(Serialize)]
+struct InstanceJson {
+    name: String,
+    class_name: String,
+    properties: BTreeMap<String, String>,
+    children: Vec<InstanceJson>,
+}
+
 pub struct UiService<F> {
     serve_session: Arc<ServeSession<F>>,
+
+    /// The hash of the bearer token set by the project's `serveAuth` field,
+    /// if any. `UiService` keeps only this hash, not the token itself; the
+    /// token still remains in memory in the project held by `serve_session`.
+    auth_token_hash: Option<blake3::Hash>,
 }
 
 impl<F: ImfsFetcher> Service for UiService<F> {
@@ -29,11 +59,60 @@ impl<F: ImfsFetcher> Service for UiService<F> {
     type Future = Box<dyn Future<Item = Response<Self::ReqBody>, Error = Self::Error> + Send>;
 
     fn call(&mut self, request: Request<Self::ReqBody>) -> Self::Future {
+        let path = request.uri().path();
+
+        // Browsers don't send `Authorization` on the requests they issue for
+        // `<img>`/`<link>` tags, so the static assets the UI itself depends
+        // on have to stay reachable without it. Their URLs are already
+        // content-hashed and carry no project data, so this doesn't weaken
+        // what `serveAuth` is protecting.
+        let is_static_asset =
+            path == LOGO_PATH.as_str() || path == ICON_PATH.as_str() || path == CSS_PATH.as_str();
+
+        if !is_static_asset {
+            if let Some(expected_hash) = &self.auth_token_hash {
+                if !Self::check_auth(request.headers(), expected_hash) {
+                    return json(
+                        ErrorResponse::unauthorized("Missing or invalid Authorization header"),
+                        StatusCode::UNAUTHORIZED,
+                    );
+                }
+            }
+        }
+
+        let if_none_match = request
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
         let response = match (request.method(), request.uri().path()) {
             (&Method::GET, "/") => self.handle_home(),
-            (&Method::GET, "/logo.png") => self.handle_logo(),
-            (&Method::GET, "/icon.png") => self.handle_icon(),
+            (&Method::GET, path) if path == LOGO_PATH.as_str() => Self::handle_asset(
+                "image/png",
+                &LOGO_HASH,
+                assets::logo(),
+                if_none_match.as_deref(),
+            ),
+            (&Method::GET, path) if path == ICON_PATH.as_str() => Self::handle_asset(
+                "image/png",
+                &ICON_HASH,
+                assets::icon(),
+                if_none_match.as_deref(),
+            ),
+            (&Method::GET, path) if path == CSS_PATH.as_str() => Self::handle_asset(
+                "text/css",
+                &CSS_HASH,
+                assets::css().as_bytes(),
+                if_none_match.as_deref(),
+            ),
             (&Method::GET, "/show-instances") => self.handle_show_instances(),
+            (&Method::GET, "/api/show-instances.json") => {
+                let tree = self.serve_session.tree();
+                let root = Self::instance_json(&tree, tree.get_root_id());
+
+                return json(root, StatusCode::OK);
+            }
             (&Method::GET, "/show-imfs") => self.handle_show_imfs(),
             (_method, path) => {
                 return json(
@@ -49,20 +128,78 @@ impl<F: ImfsFetcher> Service for UiService<F> {
 
 impl<F: ImfsFetcher> UiService<F> {
     pub fn new(serve_session: Arc<ServeSession<F>>) -> Self {
-        UiService { serve_session }
+        let auth_token_hash = serve_session
+            .project_serve_auth()
+            .map(|token| blake3::hash(token.as_bytes()));
+
+        UiService {
+            serve_session,
+            auth_token_hash,
+        }
     }
 
-    fn handle_logo(&self) -> Response<Body> {
-        Response::builder()
-            .header(header::CONTENT_TYPE, "image/png")
-            .body(Body::from(assets::logo()))
-            .unwrap()
+    /// Checks whether the request carries an `Authorization: Bearer <token>`
+    /// header whose token hashes to `expected_hash`. The comparison is done
+    /// in constant time so that response timing can't be used to guess the
+    /// token byte-by-byte.
+    fn check_auth(headers: &header::HeaderMap, expected_hash: &blake3::Hash) -> bool {
+        let token = match headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::strip_bearer_prefix)
+        {
+            Some(token) => token,
+            None => return false,
+        };
+
+        let provided_hash = blake3::hash(token.as_bytes());
+
+        provided_hash
+            .as_bytes()
+            .ct_eq(expected_hash.as_bytes())
+            .into()
+    }
+
+    /// Strips the `Bearer` auth scheme from an `Authorization` header value,
+    /// returning the token that follows it. The scheme name is matched
+    /// case-insensitively, as required by RFC 6750.
+    fn strip_bearer_prefix(value: &str) -> Option<&str> {
+        let mut parts = value.splitn(2, ' ');
+        let scheme = parts.next()?;
+        let token = parts.next()?;
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            Some(token)
+        } else {
+            None
+        }
     }
 
-    fn handle_icon(&self) -> Response<Body> {
+    /// Serves a hashed, immutable asset. Because the URL a caller used to
+    /// reach this handler embeds `hash`, a request that still carries a
+    /// matching `If-None-Match` is guaranteed to be fetching the exact same
+    /// bytes and can be answered with a plain `304`.
+    fn handle_asset(
+        content_type: &'static str,
+        hash: &str,
+        bytes: &'static [u8],
+        if_none_match: Option<&str>,
+    ) -> Response<Body> {
+        let etag = format!("\"{}\"", hash);
+
+        if if_none_match == Some(etag.as_str()) {
+            return Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, etag)
+                .body(Body::empty())
+                .unwrap();
+        }
+
         Response::builder()
-            .header(header::CONTENT_TYPE, "image/png")
-            .body(Body::from(assets::icon()))
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+            .header(header::ETAG, etag)
+            .body(Body::from(bytes))
             .unwrap()
     }
 
@@ -95,6 +232,35 @@ impl<F: ImfsFetcher> UiService<F> {
             .unwrap()
     }
 
+    /// Builds the same tree served by `/show-instances`, but as a
+    /// `Serialize`-able structure for the `/api/show-instances.json`
+    /// endpoint, so tooling can consume the live instance state
+    /// programmatically instead of scraping HTML. `serde_json` (via the
+    /// `json` helper) already escapes control characters correctly, which
+    /// keeps this valid even for names containing them.
+    fn instance_json(tree: &RojoTree, id: RbxId) -> InstanceJson {
+        let instance = tree.get_instance(id).unwrap();
+
+        let mut properties = BTreeMap::new();
+        for (key, value) in instance.properties() {
+            properties.insert(key.to_string(), Self::display_value(value));
+        }
+
+        let children = instance
+            .children()
+            .iter()
+            .copied()
+            .map(|id| Self::instance_json(tree, id))
+            .collect();
+
+        InstanceJson {
+            name: instance.name().to_owned(),
+            class_name: instance.class_name().to_owned(),
+            properties,
+            children,
+        }
+    }
+
     fn handle_show_imfs(&self) -> Response<Body> {
         let page = self.normal_page(html! {
             "TODO /show/imfs"
@@ -132,7 +298,7 @@ impl<F: ImfsFetcher> UiService<F> {
             .into_iter()
             .map(|(key, value)| {
                 html! {
-                    <div class="instance-property" title={ Self::display_value(value) }>
+                    <div class="instance-property" title={ Self::escape_attribute(&Self::display_value(value)) }>
                         { key.clone() } ": " { format!("{:?}", value.get_type()) }
                     </div>
                 }
@@ -171,6 +337,15 @@ impl<F: ImfsFetcher> UiService<F> {
         }
     }
 
+    /// Escapes double quotes so a value can't break out of the
+    /// double-quoted `title` attribute ritz emits it into. ritz's `html!`
+    /// escapes text nodes automatically, but that says nothing about
+    /// whether it escapes quotes within an attribute value, so this
+    /// attribute context needs its own guard.
+    fn escape_attribute(value: &str) -> String {
+        value.replace('"', "&quot;")
+    }
+
     fn display_value(value: &RbxValue) -> String {
         match value {
             RbxValue::String { value } => value.clone(),
@@ -211,7 +386,7 @@ impl<F: ImfsFetcher> UiService<F> {
             <div class="root">
                 <header class="header">
                     <a class="main-logo" href="/">
-                        <img src="/logo.png" />
+                        <img src={ LOGO_PATH.as_str() } />
                     </a>
                     <div class="stats">
                         { Self::stat_item("Server Version", SERVER_VERSION) }
@@ -231,11 +406,9 @@ impl<F: ImfsFetcher> UiService<F> {
             <html>
                 <head>
                     <title>"Rojo Live Server"</title>
-                    <link rel="icon" type="image/png" sizes="32x32" href="/icon.png" />
+                    <link rel="icon" type="image/png" sizes="32x32" href={ ICON_PATH.as_str() } />
+                    <link rel="stylesheet" href={ CSS_PATH.as_str() } />
                     <meta name="viewport" content="width=device-width, initial-scale=1, minimum-scale=1, maximum-scale=1" />
-                    <style>
-                        { ritz::UnescapedText::new(assets::css()) }
-                    </style>
                 </head>
 
                 <body>